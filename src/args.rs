@@ -3,6 +3,8 @@ use std::{io, path::PathBuf};
 use clap::{builder::PossibleValue, Parser, Subcommand, ValueEnum};
 use thiserror::Error;
 
+use crate::stm_device::ControlLine;
+
 #[derive(Error, Debug)]
 #[error(transparent)]
 pub enum UartControlError {
@@ -86,6 +88,21 @@ impl From<StopBits> for serialport::StopBits {
     }
 }
 
+/// Which modem control line a board's BOOT0 or NRST pin is wired to.
+#[derive(Clone, ValueEnum)]
+pub enum BootLine {
+    Rts,
+    Dtr,
+}
+impl From<BootLine> for ControlLine {
+    fn from(line: BootLine) -> Self {
+        match line {
+            BootLine::Rts => Self::Rts,
+            BootLine::Dtr => Self::Dtr,
+        }
+    }
+}
+
 #[derive(Parser)]
 pub struct Args {
     #[command(subcommand)]
@@ -116,5 +133,38 @@ pub enum Command {
         /// Stop bits after data bits
         #[arg(short, long, value_enum, default_value_t = StopBits::One)]
         stop_bits: StopBits,
+
+        /// Automatically reset the board into its bootloader via the BOOT0/NRST control lines,
+        /// instead of assuming it is already in bootloader mode
+        #[arg(long)]
+        auto_boot: bool,
+
+        /// Control line wired to BOOT0
+        #[arg(long, value_enum, default_value_t = BootLine::Dtr, requires = "auto_boot")]
+        boot0_line: BootLine,
+
+        /// Drive BOOT0's control line low (rather than high) to assert it
+        #[arg(long, requires = "auto_boot")]
+        boot0_active_low: bool,
+
+        /// Control line wired to NRST
+        #[arg(long, value_enum, default_value_t = BootLine::Rts, requires = "auto_boot")]
+        nrst_line: BootLine,
+
+        /// Drive NRST's control line high (rather than low) to assert it
+        #[arg(long, requires = "auto_boot")]
+        nrst_active_high: bool,
+
+        /// How long to hold NRST asserted during the reset pulse, in milliseconds
+        #[arg(long, default_value_t = 50, requires = "auto_boot")]
+        reset_pulse_ms: u64,
+
+        /// Enable tracing of every frame sent to and received from the device
+        #[arg(long)]
+        trace: bool,
+
+        /// Write the trace transcript to this file, in addition to stdout (requires --trace)
+        #[arg(long, requires = "trace")]
+        trace_file: Option<PathBuf>,
     },
 }
@@ -1,13 +1,13 @@
 mod args;
 mod stm_device;
 
-use std::time::Duration;
+use std::{io, time::Duration};
 
 use clap::Parser;
 use serialport::{available_ports, SerialPortType, UsbPortInfo};
 
 use args::*;
-use stm_device::{StmDevice, StmDeviceError};
+use stm_device::{BootSequence, StmDevice, StmDeviceError, Tracer};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -17,6 +17,7 @@ pub enum UartControlError {
     #[error("Unable to convert path to string")]
     PathError,
     StmDevice(#[from] StmDeviceError),
+    Io(#[from] io::Error),
 }
 
 fn main() -> Result<(), UartControlError> {
@@ -71,6 +72,14 @@ fn main() -> Result<(), UartControlError> {
                 data_bits,
                 parity,
                 stop_bits,
+                auto_boot,
+                boot0_line,
+                boot0_active_low,
+                nrst_line,
+                nrst_active_high,
+                reset_pulse_ms,
+                trace,
+                trace_file,
             } => {
                 let mut device = StmDevice::new(
                     serialport::new(
@@ -84,7 +93,24 @@ fn main() -> Result<(), UartControlError> {
                     .open()?,
                 );
 
-                device.initialise()?;
+                if trace {
+                    device.set_tracer(match trace_file {
+                        Some(path) => Tracer::to_file(path)?,
+                        None => Tracer::new(),
+                    });
+                }
+
+                if auto_boot {
+                    device.enter_bootloader(BootSequence {
+                        boot0_line: boot0_line.into(),
+                        boot0_asserted: !boot0_active_low,
+                        nrst_line: nrst_line.into(),
+                        nrst_asserted: nrst_active_high,
+                        reset_pulse: Duration::from_millis(reset_pulse_ms),
+                    })?;
+                } else {
+                    device.initialise()?;
+                }
                 println!("Device initialised successfully");
 
                 let protocol = device.get_protocol()?;
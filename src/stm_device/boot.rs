@@ -0,0 +1,48 @@
+use std::{io, time::Duration};
+
+use super::transport::SerialControl;
+
+/// A modem control line that can be wired up to a board's BOOT0 or NRST pin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlLine {
+    Rts,
+    Dtr,
+}
+impl ControlLine {
+    fn set(&self, port: &mut impl SerialControl, level: bool) -> io::Result<()> {
+        match self {
+            Self::Rts => port.write_request_to_send(level),
+            Self::Dtr => port.write_data_terminal_ready(level),
+        }
+    }
+}
+
+/// Describes how a board's BOOT0 and NRST pins are wired to the adapter's control lines, so
+/// `StmDevice::enter_bootloader` can drive the chip into its bootloader without a button press.
+#[derive(Debug, Clone, Copy)]
+pub struct BootSequence {
+    /// Control line wired to BOOT0.
+    pub boot0_line: ControlLine,
+    /// The level that asserts BOOT0 (selects the bootloader on reset).
+    pub boot0_asserted: bool,
+
+    /// Control line wired to NRST.
+    pub nrst_line: ControlLine,
+    /// The level that asserts NRST (holds the chip in reset).
+    pub nrst_asserted: bool,
+
+    /// How long to hold NRST asserted before releasing it.
+    pub reset_pulse: Duration,
+}
+impl BootSequence {
+    /// Asserts BOOT0, pulses NRST to reset the chip with BOOT0 held, then releases BOOT0.
+    pub(super) fn run(&self, port: &mut impl SerialControl) -> io::Result<()> {
+        self.boot0_line.set(port, self.boot0_asserted)?;
+
+        self.nrst_line.set(port, self.nrst_asserted)?;
+        std::thread::sleep(self.reset_pulse);
+        self.nrst_line.set(port, !self.nrst_asserted)?;
+
+        self.boot0_line.set(port, !self.boot0_asserted)
+    }
+}
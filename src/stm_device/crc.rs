@@ -0,0 +1,58 @@
+//! A minimal, table-based CRC-32 (reflected IEEE 802.3 polynomial) implementation, used to cheaply
+//! verify a block of memory without holding the whole thing in memory at once.
+
+const POLYNOMIAL: u32 = 0xEDB88320;
+
+const fn generate_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                POLYNOMIAL ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[byte] = crc;
+        byte += 1;
+    }
+
+    table
+}
+
+const TABLE: [u32; 256] = generate_table();
+
+/// Incrementally computes a CRC-32 checksum, without needing to hold the entire input in memory.
+pub struct Crc32 {
+    state: u32,
+}
+impl Crc32 {
+    pub fn new() -> Self {
+        Self { state: 0xFFFFFFFF }
+    }
+
+    /// Folds `bytes` into the running checksum.
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let index = ((self.state ^ byte as u32) & 0xFF) as usize;
+            self.state = TABLE[index] ^ (self.state >> 8);
+        }
+    }
+
+    /// Consumes the running state, returning the final checksum.
+    pub fn finalise(self) -> u32 {
+        self.state ^ 0xFFFFFFFF
+    }
+}
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
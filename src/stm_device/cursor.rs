@@ -0,0 +1,55 @@
+use super::{StmDevice, StmDeviceError, Transport};
+
+/// Declarative, typed reads over a device's response stream, replacing ad-hoc `read_byte`/
+/// `read_bytes` calls and manual length arithmetic.
+pub(super) trait ProtoRead {
+    fn read_u8(&mut self) -> Result<u8, StmDeviceError>;
+    fn read_be_u16(&mut self) -> Result<u16, StmDeviceError>;
+    fn read_be_u32(&mut self) -> Result<u32, StmDeviceError>;
+
+    /// Reads a count byte `n`, followed by the `n + 1` bytes that make up the response.
+    fn read_length_prefixed(&mut self) -> Result<Vec<u8>, StmDeviceError>;
+
+    /// Reads the ack/nack byte, turning a NACK into an error rather than a `bool`.
+    fn expect_ack(&mut self) -> Result<(), StmDeviceError>;
+}
+
+/// A cursor over a [StmDevice]'s response stream.
+pub(super) struct Cursor<'a, T: Transport> {
+    device: &'a mut StmDevice<T>,
+}
+impl<'a, T: Transport> Cursor<'a, T> {
+    pub(super) fn new(device: &'a mut StmDevice<T>) -> Self {
+        Self { device }
+    }
+}
+impl<'a, T: Transport> ProtoRead for Cursor<'a, T> {
+    fn read_u8(&mut self) -> Result<u8, StmDeviceError> {
+        self.device.read_byte()
+    }
+
+    fn read_be_u16(&mut self) -> Result<u16, StmDeviceError> {
+        let bytes = self.device.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_be_u32(&mut self) -> Result<u32, StmDeviceError> {
+        let bytes = self.device.read_bytes(4)?;
+        Ok(u32::from_be_bytes(
+            bytes.try_into().expect("read_bytes(4) returns 4 bytes"),
+        ))
+    }
+
+    fn read_length_prefixed(&mut self) -> Result<Vec<u8>, StmDeviceError> {
+        let count = self.read_u8()? as usize + 1;
+        self.device.read_bytes(count)
+    }
+
+    fn expect_ack(&mut self) -> Result<(), StmDeviceError> {
+        if self.device.get_ack()? {
+            Ok(())
+        } else {
+            Err(StmDeviceError::Nack)
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io,
+    time::Duration,
+};
+
+use super::protocol::CommonCommand;
+use super::transport::Transport;
+use super::{ACK, NACK};
+
+/// Flash is erased in `PAGE_SIZE`-byte pages, mirroring a typical STM32 sector size.
+const PAGE_SIZE: u32 = 1024;
+
+enum ReadMemoryState {
+    AwaitingAddress,
+    AwaitingLength(u32),
+}
+
+enum WriteMemoryState {
+    AwaitingAddress,
+    AwaitingData(u32),
+}
+
+enum PendingCommand {
+    ReadMemory(ReadMemoryState),
+    WriteMemory(WriteMemoryState),
+    Go,
+    ExtendedErase,
+}
+
+/// A [Transport] that emulates an STM32 USART bootloader entirely in memory, so the protocol
+/// state machine can be exercised in tests and dry runs without any hardware attached.
+pub struct MockBootloader {
+    tx: VecDeque<u8>,
+    pending: Option<PendingCommand>,
+
+    flash: BTreeMap<u32, u8>,
+
+    version: u8,
+    product_id: u16,
+
+    /// Commands that should be NACKed a fixed number of times before succeeding, keyed by
+    /// command byte.
+    nacks: HashMap<u8, usize>,
+    /// Commands whose response bytes should be dropped (simulating a flaky link) a fixed number
+    /// of times before succeeding, keyed by command byte.
+    drops: HashMap<u8, usize>,
+}
+impl MockBootloader {
+    pub fn new(version: u8, product_id: u16) -> Self {
+        Self {
+            tx: VecDeque::new(),
+            pending: None,
+            flash: BTreeMap::new(),
+            version,
+            product_id,
+            nacks: HashMap::new(),
+            drops: HashMap::new(),
+        }
+    }
+
+    /// NACKs the next `times` attempts to issue `command`.
+    pub fn nack_next(&mut self, command: u8, times: usize) {
+        self.nacks.insert(command, times);
+    }
+
+    /// Drops the response to the next `times` attempts to issue `command`, simulating a dropped
+    /// link so that callers observe an I/O error and retry.
+    pub fn drop_next(&mut self, command: u8, times: usize) {
+        self.drops.insert(command, times);
+    }
+
+    /// Reads a single byte out of the emulated flash, defaulting to the erased value of `0xFF`.
+    pub fn read_flash(&self, address: u32) -> u8 {
+        self.flash.get(&address).copied().unwrap_or(0xFF)
+    }
+
+    fn handle_frame(&mut self, frame: &[u8]) {
+        match self.pending.take() {
+            None => self.handle_command_frame(frame),
+            Some(pending) => self.handle_payload_frame(pending, frame),
+        }
+    }
+
+    fn handle_command_frame(&mut self, frame: &[u8]) {
+        if frame == [0x7F] {
+            self.tx.push_back(ACK);
+            return;
+        }
+
+        let &[command, complement] = frame else {
+            return;
+        };
+        if command != !complement {
+            self.tx.push_back(NACK);
+            return;
+        }
+
+        if let Some(remaining) = self
+            .nacks
+            .get_mut(&command)
+            .filter(|remaining| **remaining > 0)
+        {
+            *remaining -= 1;
+            self.tx.push_back(NACK);
+            return;
+        }
+
+        if let Some(remaining) = self
+            .drops
+            .get_mut(&command)
+            .filter(|remaining| **remaining > 0)
+        {
+            *remaining -= 1;
+            // Drop the response entirely, leaving the caller to time out / hit an I/O error.
+            return;
+        }
+
+        self.tx.push_back(ACK);
+
+        match CommonCommand::try_from(command) {
+            Ok(CommonCommand::Get) => self.respond_get(),
+            Ok(CommonCommand::GetVersion) => self.respond_get_version(),
+            Ok(CommonCommand::GetId) => self.respond_get_id(),
+            Ok(CommonCommand::ReadMemory) => {
+                self.pending = Some(PendingCommand::ReadMemory(ReadMemoryState::AwaitingAddress))
+            }
+            Ok(CommonCommand::WriteMemory) => {
+                self.pending = Some(PendingCommand::WriteMemory(
+                    WriteMemoryState::AwaitingAddress,
+                ))
+            }
+            Ok(CommonCommand::Go) => self.pending = Some(PendingCommand::Go),
+            Ok(CommonCommand::ExtendedErase) => self.pending = Some(PendingCommand::ExtendedErase),
+            _ => (),
+        }
+    }
+
+    /// Handles a payload frame that follows a command byte. Address, data and erase payloads are
+    /// sent via `StmDevice::send_bytes` and carry an XOR checksum; a bare length byte is sent via
+    /// `StmDevice::send_byte` and carries its complement instead.
+    fn handle_payload_frame(&mut self, pending: PendingCommand, frame: &[u8]) {
+        if let PendingCommand::ReadMemory(ReadMemoryState::AwaitingLength(address)) = pending {
+            let &[byte_count, complement] = frame else {
+                self.tx.push_back(NACK);
+                return;
+            };
+            if byte_count != !complement {
+                self.tx.push_back(NACK);
+                return;
+            }
+
+            self.tx.push_back(ACK);
+            for offset in 0..=(byte_count as u32) {
+                let byte = self.read_flash(address + offset);
+                self.tx.push_back(byte);
+            }
+            return;
+        }
+
+        if !checksum_ok(frame) {
+            self.tx.push_back(NACK);
+            return;
+        }
+        let payload = &frame[..frame.len() - 1];
+
+        match pending {
+            PendingCommand::ReadMemory(ReadMemoryState::AwaitingLength(_)) => unreachable!(),
+            PendingCommand::ReadMemory(ReadMemoryState::AwaitingAddress) => {
+                let address = u32::from_be_bytes(payload.try_into().unwrap());
+                self.tx.push_back(ACK);
+                self.pending = Some(PendingCommand::ReadMemory(ReadMemoryState::AwaitingLength(
+                    address,
+                )));
+            }
+            PendingCommand::WriteMemory(WriteMemoryState::AwaitingAddress) => {
+                let address = u32::from_be_bytes(payload.try_into().unwrap());
+                self.tx.push_back(ACK);
+                self.pending = Some(PendingCommand::WriteMemory(WriteMemoryState::AwaitingData(
+                    address,
+                )));
+            }
+            PendingCommand::WriteMemory(WriteMemoryState::AwaitingData(address)) => {
+                let data = &payload[1..];
+                for (offset, &byte) in data.iter().enumerate() {
+                    self.flash.insert(address + offset as u32, byte);
+                }
+                self.tx.push_back(ACK);
+            }
+            PendingCommand::Go => {
+                // Nothing to emulate beyond acknowledging the jump target.
+                self.tx.push_back(ACK);
+            }
+            PendingCommand::ExtendedErase => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                match code {
+                    // Global/Bank1/Bank2 are all treated as a full erase: the mock flash map
+                    // isn't actually partitioned into banks, so a bank-scoped erase here clears
+                    // everything rather than just that bank's pages.
+                    0xFFFD..=0xFFFF => self.flash.clear(),
+                    _ => {
+                        let page_count = code as usize + 1;
+                        for page_bytes in payload[2..].chunks(2).take(page_count) {
+                            let page = u16::from_be_bytes([page_bytes[0], page_bytes[1]]);
+                            let base = page as u32 * PAGE_SIZE;
+                            for address in base..base + PAGE_SIZE {
+                                self.flash.remove(&address);
+                            }
+                        }
+                    }
+                }
+                self.tx.push_back(ACK);
+            }
+        }
+    }
+
+    fn respond_get(&mut self) {
+        let commands = [
+            CommonCommand::Get as u8,
+            CommonCommand::GetVersion as u8,
+            CommonCommand::GetId as u8,
+            CommonCommand::ReadMemory as u8,
+            CommonCommand::Go as u8,
+            CommonCommand::WriteMemory as u8,
+            CommonCommand::ExtendedErase as u8,
+        ];
+
+        self.tx.push_back(commands.len() as u8);
+        self.tx.push_back(self.version);
+        self.tx.extend(commands);
+        self.tx.push_back(ACK);
+    }
+
+    fn respond_get_version(&mut self) {
+        self.tx.push_back(self.version);
+        self.tx.push_back(0x00);
+        self.tx.push_back(0x00);
+        self.tx.push_back(ACK);
+    }
+
+    fn respond_get_id(&mut self) {
+        self.tx.push_back(1);
+        self.tx.extend(self.product_id.to_be_bytes());
+        self.tx.push_back(ACK);
+    }
+}
+impl Transport for MockBootloader {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        if self.tx.len() < buf.len() {
+            self.tx.clear();
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "no response queued",
+            ));
+        }
+
+        for slot in buf.iter_mut() {
+            *slot = self.tx.pop_front().expect("checked length above");
+        }
+
+        Ok(())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.handle_frame(buf);
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Checks that the last byte of `frame` is the XOR checksum of the bytes preceding it.
+fn checksum_ok(frame: &[u8]) -> bool {
+    match frame.split_last() {
+        Some((checksum, payload)) => payload.iter().fold(0, |acc, &b| acc ^ b) == *checksum,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stm_device::{StmDevice, StmDeviceError};
+
+    #[test]
+    fn write_then_read_round_trips() {
+        let mut device = StmDevice::new(MockBootloader::new(0x10, 0x0410));
+        device.initialise().unwrap();
+
+        let address = 0x0800_0000;
+        let data: Vec<u8> = (0..=8).collect();
+        device.write_memory(address, &data).unwrap();
+
+        let read = device.read_memory(address, (data.len() - 1) as u8).unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn verify_matches_after_write() {
+        let mut device = StmDevice::new(MockBootloader::new(0x10, 0x0410));
+        device.initialise().unwrap();
+
+        let address = 0x0800_0000;
+        let data: Vec<u8> = (0..=255).collect();
+        device.write_memory(address, &data).unwrap();
+
+        let result = device.verify(address, &data).unwrap();
+        assert!(result.matches);
+        assert_eq!(result.expected_crc, result.actual_crc);
+    }
+
+    #[test]
+    fn nacked_command_fails_without_retry() {
+        let mut mock = MockBootloader::new(0x10, 0x0410);
+        mock.nack_next(CommonCommand::Go as u8, 1);
+
+        let mut device = StmDevice::new(mock);
+        device.initialise().unwrap();
+
+        let err = device.go(0x0800_0000).unwrap_err();
+        assert!(matches!(err, StmDeviceError::CommandFail(_)));
+    }
+
+    #[test]
+    fn dropped_response_is_retried_until_success() {
+        let mut mock = MockBootloader::new(0x10, 0x0410);
+        // Fewer drops than the device's retry budget, so it should recover.
+        mock.drop_next(CommonCommand::Go as u8, 2);
+
+        let mut device = StmDevice::new(mock);
+        device.initialise().unwrap();
+
+        device.go(0x0800_0000).unwrap();
+    }
+
+    #[test]
+    fn dropped_response_exceeding_retries_fails() {
+        let mut mock = MockBootloader::new(0x10, 0x0410);
+        // More drops than the device's retry budget, so it should give up.
+        mock.drop_next(CommonCommand::Go as u8, 10);
+
+        let mut device = StmDevice::new(mock);
+        device.initialise().unwrap();
+
+        let err = device.go(0x0800_0000).unwrap_err();
+        assert!(matches!(err, StmDeviceError::RetryExceeded(_)));
+    }
+}
@@ -1,11 +1,24 @@
+mod boot;
+mod crc;
+mod cursor;
+mod mock;
 mod protocol;
+mod trace;
+mod transport;
 
 use std::io;
 
-use serialport::SerialPort;
 use thiserror::Error;
 
+pub use self::boot::{BootSequence, ControlLine};
+use self::crc::Crc32;
+use self::cursor::{Cursor, ProtoRead};
 use self::protocol::{Command, CommonCommand, Protocol, ProtocolError, ProtocolVersion};
+pub use self::trace::{Direction, Tracer};
+pub use self::transport::{SerialControl, Transport};
+
+/// The largest chunk `read_memory` can service in a single request.
+const MAX_READ_CHUNK: usize = 256;
 
 const ACK: u8 = 0x79;
 const NACK: u8 = 0x1F;
@@ -22,6 +35,14 @@ pub enum StmDeviceError {
     CommandFail(Command),
     #[error("retries exceeded to run command: {0:?}")]
     RetryExceeded(Command),
+    #[error("data length {0} is outside the valid range of 1..=256 bytes")]
+    InvalidDataLength(usize),
+    #[error("page count {0} is outside the valid range of 1..=65533 pages")]
+    InvalidPageCount(usize),
+    #[error("device replied with NACK")]
+    Nack,
+    #[error("BOOT0 and NRST cannot be wired to the same control line")]
+    ConflictingControlLines,
     #[error(transparent)]
     ProtocolError(#[from] ProtocolError),
     #[error(transparent)]
@@ -44,30 +65,61 @@ impl From<&[u8]> for ProductId {
     }
 }
 
-pub struct StmDevice {
-    port: Box<dyn SerialPort>,
+/// Selects which region `StmDevice::extended_erase` should target.
+pub enum EraseRequest {
+    /// Erases the entire flash memory.
+    Global,
+    /// Erases only the first flash bank.
+    Bank1,
+    /// Erases only the second flash bank.
+    Bank2,
+    /// Erases a specific, non-contiguous list of pages.
+    Pages(Vec<u16>),
+}
+
+/// The outcome of comparing a region of device flash against an expected image via CRC-32.
+pub struct VerifyResult {
+    /// Whether the expected and actual checksums matched.
+    pub matches: bool,
+    /// The checksum computed over `expected`.
+    pub expected_crc: u32,
+    /// The checksum computed over the bytes read back from the device.
+    pub actual_crc: u32,
+}
+
+pub struct StmDevice<T: Transport> {
+    port: T,
 
     initialised: bool,
     protocol_version: ProtocolVersion,
 
     retry_amount: usize,
+
+    tracer: Option<Tracer>,
 }
-impl StmDevice {
-    pub fn new(port: Box<dyn SerialPort>) -> Self {
+impl<T: Transport> StmDevice<T> {
+    pub fn new(port: T) -> Self {
         Self {
             port,
             initialised: false,
             protocol_version: ProtocolVersion::unknown(),
             retry_amount: 5,
+            tracer: None,
         }
     }
 
+    /// Enables recording of every frame sent to and received from the device.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
     pub fn initialise(&mut self) -> Result<(), StmDeviceError> {
         if self.initialised {
             Err(StmDeviceError::AlreadyInitialised)
         } else {
             // Send wake up byte
             self.port.write_all(&[0x7F])?;
+            self.trace(Direction::Tx, Some("Sync"), &[0x7F]);
 
             // Wait for response
             while !self.get_ack()? {}
@@ -79,15 +131,28 @@ impl StmDevice {
         }
     }
 
+    /// Drives the board's BOOT0/NRST wiring through `sequence` to reset the chip into its
+    /// bootloader, then performs the usual [StmDevice::initialise] handshake.
+    pub fn enter_bootloader(&mut self, sequence: BootSequence) -> Result<(), StmDeviceError>
+    where
+        T: SerialControl,
+    {
+        if sequence.boot0_line == sequence.nrst_line {
+            return Err(StmDeviceError::ConflictingControlLines);
+        }
+
+        sequence.run(&mut self.port)?;
+
+        self.initialise()
+    }
+
     pub fn get_protocol(&mut self) -> Result<Protocol, StmDeviceError> {
         // Send the command
         self.retry_command(Command::Common(CommonCommand::Get))?;
 
-        // Get the number of bytes in the response
-        let response_bytes = self.read_byte()? + 1;
-        let response = self.read_bytes(response_bytes as usize)?;
-
-        self.get_ack().ok();
+        let mut cursor = Cursor::new(self);
+        let response = cursor.read_length_prefixed()?;
+        cursor.expect_ack().ok();
 
         // Attempt to determine protocol version
         Ok(Protocol::try_from(response.as_slice())?)
@@ -96,22 +161,26 @@ impl StmDevice {
     pub fn get_version(&mut self) -> Result<ProtocolVersion, StmDeviceError> {
         self.retry_command(Command::Common(CommonCommand::Get))?;
 
-        let response = self.read_bytes(3)?;
-
-        self.get_ack().ok();
+        let version = {
+            let mut cursor = Cursor::new(self);
+            let version = cursor.read_u8()?;
+            let _option_bytes = cursor.read_be_u16()?;
+            cursor.expect_ack().ok();
+            version
+        };
 
-        self.protocol_version = ProtocolVersion::from(response[0]);
+        self.protocol_version = ProtocolVersion::from(version);
         Ok(self.protocol_version.clone())
     }
 
     pub fn get_id(&mut self) -> Result<ProductId, StmDeviceError> {
         self.retry_command(Command::Common(CommonCommand::GetId))?;
 
-        let response_size = self.read_byte()? + 1;
-        let product_id = ProductId::from(self.read_bytes(response_size as usize)?.as_slice());
-        self.get_ack().ok();
+        let mut cursor = Cursor::new(self);
+        let response = cursor.read_length_prefixed()?;
+        cursor.expect_ack().ok();
 
-        Ok(product_id)
+        Ok(ProductId::from(response.as_slice()))
     }
 
     pub fn read_memory(&mut self, address: u32, byte_count: u8) -> Result<Vec<u8>, StmDeviceError> {
@@ -127,6 +196,92 @@ impl StmDevice {
         self.read_bytes(byte_count as usize + 1)
     }
 
+    /// Writes up to 256 bytes to the device starting at `address`.
+    pub fn write_memory(&mut self, address: u32, data: &[u8]) -> Result<(), StmDeviceError> {
+        if !(1..=256).contains(&data.len()) {
+            return Err(StmDeviceError::InvalidDataLength(data.len()));
+        }
+
+        self.retry_command(Command::Common(CommonCommand::WriteMemory))?;
+
+        // Send the address
+        self.send_bytes(&address.to_be_bytes())?;
+
+        // Send the length byte followed by the data, with a checksum over both
+        let mut payload = Vec::with_capacity(data.len() + 1);
+        payload.push((data.len() - 1) as u8);
+        payload.extend_from_slice(data);
+        self.send_bytes(&payload)
+    }
+
+    /// Instructs the device to jump to and begin executing code at `address`.
+    pub fn go(&mut self, address: u32) -> Result<(), StmDeviceError> {
+        self.retry_command(Command::Common(CommonCommand::Go))?;
+
+        self.send_bytes(&address.to_be_bytes())
+    }
+
+    /// Erases flash memory, either as a mass erase of the whole chip/a bank, or a specific list
+    /// of pages.
+    pub fn extended_erase(&mut self, request: EraseRequest) -> Result<(), StmDeviceError> {
+        self.retry_command(Command::Common(CommonCommand::ExtendedErase))?;
+
+        match request {
+            EraseRequest::Global => self.send_bytes(&[0xFF, 0xFF]),
+            EraseRequest::Bank1 => self.send_bytes(&[0xFF, 0xFE]),
+            EraseRequest::Bank2 => self.send_bytes(&[0xFF, 0xFD]),
+            EraseRequest::Pages(pages) => {
+                // A count byte pair of 0xFFFD..=0xFFFF is reserved for the bank/global erase codes
+                if !(1..=0xFFFD).contains(&pages.len()) {
+                    return Err(StmDeviceError::InvalidPageCount(pages.len()));
+                }
+
+                let mut payload = Vec::with_capacity((pages.len() + 1) * 2);
+                payload.extend_from_slice(&((pages.len() - 1) as u16).to_be_bytes());
+                for page in pages {
+                    payload.extend_from_slice(&page.to_be_bytes());
+                }
+
+                self.send_bytes(&payload)
+            }
+        }
+    }
+
+    /// Reads back `expected.len()` bytes from `address` and compares a running CRC-32 against
+    /// `expected`, without holding the whole read-back region in memory at once.
+    pub fn verify(
+        &mut self,
+        mut address: u32,
+        expected: &[u8],
+    ) -> Result<VerifyResult, StmDeviceError> {
+        let mut expected_crc = Crc32::new();
+        let mut actual_crc = Crc32::new();
+
+        for chunk in expected.chunks(MAX_READ_CHUNK) {
+            let read = self.read_memory(address, (chunk.len() - 1) as u8)?;
+
+            expected_crc.update(chunk);
+            actual_crc.update(&read);
+
+            address += chunk.len() as u32;
+        }
+
+        let expected_crc = expected_crc.finalise();
+        let actual_crc = actual_crc.finalise();
+
+        Ok(VerifyResult {
+            matches: expected_crc == actual_crc,
+            expected_crc,
+            actual_crc,
+        })
+    }
+
+    fn trace(&mut self, direction: Direction, label: Option<&str>, bytes: &[u8]) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.record(direction, label, bytes);
+        }
+    }
+
     fn retry_command(&mut self, command: Command) -> Result<(), StmDeviceError> {
         for _ in 0..self.retry_amount {
             match self.send_command(command) {
@@ -144,7 +299,9 @@ impl StmDevice {
     /// `false` indicates that the command was discarded.
     fn send_command(&mut self, command: Command) -> Result<bool, StmDeviceError> {
         if self.initialised {
-            self.port.write_all(&Vec::from(command))?;
+            let bytes = Vec::from(command);
+            self.port.write_all(&bytes)?;
+            self.trace(Direction::Tx, Some(&format!("{command:?}")), &bytes);
 
             self.get_ack()
         } else {
@@ -155,29 +312,47 @@ impl StmDevice {
     /// Attempts to read an `ACK` from the device, returning `true` if one is found, otherwise
     /// returning `false`. [StmDeviceError] is returned
     fn get_ack(&mut self) -> Result<bool, StmDeviceError> {
-        match self.read_byte()? {
-            ACK => Ok(true),
-            NACK => Ok(false),
-            b => Err(StmDeviceError::ExpectedAck(b)),
+        let mut buf = [0u8; 1];
+        self.port.read_exact(&mut buf)?;
+
+        match buf[0] {
+            ACK => {
+                self.trace(Direction::Rx, Some("ACK"), &buf);
+                Ok(true)
+            }
+            NACK => {
+                self.trace(Direction::Rx, Some("NACK"), &buf);
+                Ok(false)
+            }
+            b => {
+                self.trace(Direction::Rx, Some("Unknown"), &buf);
+                Err(StmDeviceError::ExpectedAck(b))
+            }
         }
     }
 
     fn read_byte(&mut self) -> Result<u8, StmDeviceError> {
         let mut buf = [0u8; 1];
         self.port.read_exact(&mut buf)?;
+        self.trace(Direction::Rx, None, &buf);
+
         Ok(buf[0])
     }
 
     fn read_bytes(&mut self, amount: usize) -> Result<Vec<u8>, StmDeviceError> {
         let mut buf = vec![0u8; amount];
         self.port.read_exact(&mut buf)?;
+        self.trace(Direction::Rx, None, &buf);
+
         Ok(buf)
     }
 
     /// Calculates the checksum for a single byte (the compliment of it), and sends it to the
     /// device, and finally waits for an ACK.
     fn send_byte(&mut self, byte: u8) -> Result<(), StmDeviceError> {
-        self.port.write_all(&[byte, !byte])?;
+        let bytes = [byte, !byte];
+        self.port.write_all(&bytes)?;
+        self.trace(Direction::Tx, Some("Payload"), &bytes);
         self.get_ack()?;
 
         Ok(())
@@ -185,11 +360,10 @@ impl StmDevice {
 
     /// Calculates the checksum for the bytes, and sends it all to the device, waiting for an ACK.
     fn send_bytes(&mut self, bytes: &[u8]) -> Result<(), StmDeviceError> {
-        self.port.write_all(dbg!(&[
-            bytes,
-            &[bytes.iter().fold(0, |checksum, &b| checksum ^ b)]
-        ]
-        .concat()))?;
+        let payload = [bytes, &[bytes.iter().fold(0, |checksum, &b| checksum ^ b)]].concat();
+
+        self.port.write_all(&payload)?;
+        self.trace(Direction::Tx, Some("Payload"), &payload);
         self.get_ack()?;
 
         Ok(())
@@ -0,0 +1,80 @@
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Which way a frame crossed the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+/// A single frame captured by a [Tracer].
+pub struct Frame {
+    pub direction: Direction,
+    /// A decoded `Command`/`Response` label, where one was known at the point of capture.
+    pub label: Option<String>,
+    pub bytes: Vec<u8>,
+}
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Tx => "TX",
+            Direction::Rx => "RX",
+        };
+        let hex = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        match &self.label {
+            Some(label) => write!(f, "{direction} [{label}] {hex}"),
+            None => write!(f, "{direction} {hex}"),
+        }
+    }
+}
+
+/// Records every frame that crosses the wire, so a flaky link or an unknown device can be
+/// debugged by seeing exactly what bytes were sent and received and which ACK/NACK each command
+/// got. Enabled on `StmDevice` with [StmDevice::set_tracer](super::StmDevice::set_tracer).
+#[derive(Default)]
+pub struct Tracer {
+    frames: Vec<Frame>,
+    sink: Option<fs::File>,
+}
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Additionally writes each frame, as it is captured, to the file at `path`.
+    pub fn to_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            frames: Vec::new(),
+            sink: Some(fs::File::create(path)?),
+        })
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub(super) fn record(&mut self, direction: Direction, label: Option<&str>, bytes: &[u8]) {
+        let frame = Frame {
+            direction,
+            label: label.map(str::to_string),
+            bytes: bytes.to_vec(),
+        };
+
+        println!("{frame}");
+        if let Some(sink) = &mut self.sink {
+            let _ = writeln!(sink, "{frame}");
+        }
+
+        self.frames.push(frame);
+    }
+}
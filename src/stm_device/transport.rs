@@ -0,0 +1,50 @@
+use std::{io, time::Duration};
+
+use serialport::SerialPort;
+
+/// A byte-oriented link capable of carrying the bootloader protocol, decoupling `StmDevice` from
+/// any particular transport implementation (serial, TCP, USB bulk, an in-memory pipe, ...).
+pub trait Transport {
+    /// Fills `buf` completely, blocking until enough bytes are available.
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Writes all of `buf` to the transport.
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()>;
+
+    /// Sets the duration to wait for a read or write before timing out.
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        io::Read::read_exact(self.as_mut(), buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        io::Write::write_all(self.as_mut(), buf)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        SerialPort::set_timeout(self.as_mut(), timeout)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+}
+
+/// An extension to [Transport] for links that expose RTS/DTR-style modem control lines, used to
+/// drive a board's BOOT0/NRST wiring into the bootloader without pressing any buttons by hand.
+pub trait SerialControl {
+    fn write_request_to_send(&mut self, level: bool) -> io::Result<()>;
+    fn write_data_terminal_ready(&mut self, level: bool) -> io::Result<()>;
+}
+
+impl SerialControl for Box<dyn SerialPort> {
+    fn write_request_to_send(&mut self, level: bool) -> io::Result<()> {
+        SerialPort::write_request_to_send(self.as_mut(), level)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> io::Result<()> {
+        SerialPort::write_data_terminal_ready(self.as_mut(), level)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+}